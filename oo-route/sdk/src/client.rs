@@ -0,0 +1,122 @@
+//! Fetching pool state over RPC
+
+use {
+    crate::pool::PoolSnapshot,
+    solana_account_decoder::UiAccountEncoding,
+    solana_client::{
+        rpc_client::RpcClient,
+        rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+        rpc_filter::RpcFilterType,
+        rpc_request::MAX_MULTIPLE_ACCOUNTS,
+    },
+    solana_program::{program_pack::Pack, pubkey::Pubkey},
+    spl_token::state::Account,
+    spl_token_swap::state::{SwapState, SwapVersion},
+};
+
+/// Error returned by the RPC helpers
+pub type Error = Box<dyn std::error::Error>;
+
+/// Fetch a swap account and its token accounts, returning a snapshot of the
+/// pool
+pub fn get_pool_snapshot(
+    rpc_client: &RpcClient,
+    swap_address: &Pubkey,
+) -> Result<PoolSnapshot, Error> {
+    let swap_data = rpc_client.get_account_data(swap_address)?;
+    let swap = SwapVersion::unpack(swap_data.as_slice())
+        .map_err(|err| format!("Invalid swap {}: {}", swap_address, err))?;
+    let token_a_amount = get_token_amount(rpc_client, swap.token_a_account())?;
+    let token_b_amount = get_token_amount(rpc_client, swap.token_b_account())?;
+    let snapshot =
+        PoolSnapshot::from_swap(*swap_address, swap.as_ref(), token_a_amount, token_b_amount)
+            .map_err(|err| format!("Invalid swap {}: {}", swap_address, err))?;
+    Ok(snapshot)
+}
+
+/// Fetch snapshots for all of the given swap accounts
+pub fn get_pool_snapshots(
+    rpc_client: &RpcClient,
+    swap_addresses: &[Pubkey],
+) -> Result<Vec<PoolSnapshot>, Error> {
+    swap_addresses
+        .iter()
+        .map(|swap_address| get_pool_snapshot(rpc_client, swap_address))
+        .collect()
+}
+
+/// Fetch snapshots for every initialized swap account owned by
+/// `swap_program_id`, for example to build a `RouteGraph`.
+///
+/// Pools whose token accounts are missing or cannot be read, or whose curve
+/// cannot be decoded, are skipped.
+pub fn get_all_pool_snapshots(
+    rpc_client: &RpcClient,
+    swap_program_id: &Pubkey,
+) -> Result<Vec<PoolSnapshot>, Error> {
+    let accounts = rpc_client.get_program_accounts_with_config(
+        swap_program_id,
+        RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::DataSize(
+                SwapVersion::LATEST_LEN as u64,
+            )]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..RpcAccountInfoConfig::default()
+            },
+        },
+    )?;
+    let swaps: Vec<(Pubkey, Box<dyn SwapState>)> = accounts
+        .into_iter()
+        .filter_map(|(address, account)| {
+            SwapVersion::unpack(account.data.as_slice())
+                .ok()
+                .filter(|swap| swap.is_initialized())
+                .map(|swap| (address, swap))
+        })
+        .collect();
+
+    let token_addresses: Vec<Pubkey> = swaps
+        .iter()
+        .flat_map(|(_, swap)| vec![*swap.token_a_account(), *swap.token_b_account()])
+        .collect();
+    let token_amounts = get_token_amounts(rpc_client, &token_addresses)?;
+    Ok(swaps
+        .iter()
+        .zip(token_amounts.chunks(2))
+        .filter_map(
+            |((address, swap), token_amounts)| match (token_amounts[0], token_amounts[1]) {
+                (Some(token_a_amount), Some(token_b_amount)) => {
+                    PoolSnapshot::from_swap(*address, swap.as_ref(), token_a_amount, token_b_amount)
+                        .ok()
+                }
+                _ => None,
+            },
+        )
+        .collect())
+}
+
+fn get_token_amount(rpc_client: &RpcClient, token_account_address: &Pubkey) -> Result<u64, Error> {
+    let account_data = rpc_client.get_account_data(token_account_address)?;
+    let token_account = Account::unpack_from_slice(account_data.as_slice())
+        .map_err(|err| format!("Invalid token account {}: {}", token_account_address, err))?;
+    Ok(token_account.amount)
+}
+
+/// Balances of the given token accounts, fetched in batches, with `None` for
+/// the accounts that are missing or not token accounts
+fn get_token_amounts(
+    rpc_client: &RpcClient,
+    token_account_addresses: &[Pubkey],
+) -> Result<Vec<Option<u64>>, Error> {
+    let mut token_amounts = Vec::with_capacity(token_account_addresses.len());
+    for addresses in token_account_addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = rpc_client.get_multiple_accounts(addresses)?;
+        token_amounts.extend(accounts.into_iter().map(|account| {
+            account
+                .and_then(|account| Account::unpack(account.data.as_slice()).ok())
+                .map(|token_account| token_account.amount)
+        }));
+    }
+    Ok(token_amounts)
+}
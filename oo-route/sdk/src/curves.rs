@@ -0,0 +1,281 @@
+//! Curve evaluators for pricing pools from any program
+
+use {
+    solana_program::{program_error::ProgramError, program_pack::Pack},
+    spl_token_swap::{
+        curve::{
+            base::{CurveType, SwapCurve},
+            calculator::TradeDirection,
+            constant_price::ConstantPriceCurve,
+            constant_product::ConstantProductCurve,
+            fees::Fees,
+            offset::OffsetCurve,
+            stable::StableCurve,
+        },
+        utils::{constant_product_spot_amount_out, swap_curve_amount_in},
+    },
+    std::convert::TryFrom,
+};
+
+/// Pricing curve of a pool, independent of the program hosting it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PoolCurve {
+    /// Uniswap-style constant product curve
+    ConstantProduct,
+    /// Flat price, `token_b_price` token A for one token B
+    ConstantPrice {
+        /// Amount of token A required to get 1 token B
+        token_b_price: u64,
+    },
+    /// Stable-swap curve
+    Stable {
+        /// Amplification coefficient
+        amp: u64,
+    },
+    /// Constant product curve with a virtual amount added to token B
+    Offset {
+        /// Amount added to the token B reserve
+        token_b_offset: u64,
+    },
+}
+
+impl PoolCurve {
+    /// Read the curve of a token-swap pool, decoding its parameters with the
+    /// calculator matching its curve type
+    pub fn from_swap_curve(swap_curve: &SwapCurve) -> Result<Self, ProgramError> {
+        let mut data = [0u8; SwapCurve::LEN];
+        swap_curve.pack_into_slice(&mut data);
+        // the calculator follows the curve type
+        let calculator = &data[1..];
+        Ok(match swap_curve.curve_type {
+            CurveType::ConstantProduct => Self::ConstantProduct,
+            CurveType::ConstantPrice => Self::ConstantPrice {
+                token_b_price: ConstantPriceCurve::unpack_from_slice(calculator)?.token_b_price,
+            },
+            CurveType::Stable => Self::Stable {
+                amp: StableCurve::unpack_from_slice(calculator)?.amp,
+            },
+            CurveType::Offset => Self::Offset {
+                token_b_offset: OffsetCurve::unpack_from_slice(calculator)?.token_b_offset,
+            },
+        })
+    }
+
+    /// Token-swap curve performing the same calculation
+    pub fn to_swap_curve(&self) -> SwapCurve {
+        match *self {
+            Self::ConstantProduct => SwapCurve {
+                curve_type: CurveType::ConstantProduct,
+                calculator: Box::new(ConstantProductCurve),
+            },
+            Self::ConstantPrice { token_b_price } => SwapCurve {
+                curve_type: CurveType::ConstantPrice,
+                calculator: Box::new(ConstantPriceCurve { token_b_price }),
+            },
+            Self::Stable { amp } => SwapCurve {
+                curve_type: CurveType::Stable,
+                calculator: Box::new(StableCurve { amp }),
+            },
+            Self::Offset { token_b_offset } => SwapCurve {
+                curve_type: CurveType::Offset,
+                calculator: Box::new(OffsetCurve { token_b_offset }),
+            },
+        }
+    }
+
+    /// Amount of destination token received for `amount_in` at the current
+    /// price of the curve, before slippage and fees, used as the reference
+    /// for price impact.  Returns `None` if the price cannot be computed.
+    pub fn spot_amount_out(
+        &self,
+        amount_in: u64,
+        source_reserve: u64,
+        destination_reserve: u64,
+        trade_direction: TradeDirection,
+    ) -> Option<u128> {
+        let amount_in = u128::from(amount_in);
+        let source_reserve = u128::from(source_reserve);
+        let destination_reserve = u128::from(destination_reserve);
+        match *self {
+            Self::ConstantProduct => {
+                constant_product_spot_amount_out(amount_in, source_reserve, destination_reserve)
+            }
+            Self::ConstantPrice { token_b_price } => match trade_direction {
+                TradeDirection::AtoB => amount_in.checked_div(u128::from(token_b_price)),
+                TradeDirection::BtoA => amount_in.checked_mul(u128::from(token_b_price)),
+            },
+            Self::Stable { amp } => {
+                StableCurve { amp }.spot_amount_out(amount_in, source_reserve, destination_reserve)
+            }
+            Self::Offset { token_b_offset } => {
+                let token_b_offset = u128::from(token_b_offset);
+                match trade_direction {
+                    TradeDirection::AtoB => constant_product_spot_amount_out(
+                        amount_in,
+                        source_reserve,
+                        destination_reserve.checked_add(token_b_offset)?,
+                    ),
+                    TradeDirection::BtoA => constant_product_spot_amount_out(
+                        amount_in,
+                        source_reserve.checked_add(token_b_offset)?,
+                        destination_reserve,
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Smallest amount of source token the curve swaps into at least
+    /// `amount_out` destination tokens after `fees`, or `None` if no amount
+    /// is enough
+    pub fn quote_amount_in(
+        &self,
+        amount_out: u64,
+        source_reserve: u64,
+        destination_reserve: u64,
+        trade_direction: TradeDirection,
+        fees: &Fees,
+    ) -> Option<u64> {
+        swap_curve_amount_in(
+            &self.to_swap_curve(),
+            fees,
+            amount_out,
+            source_reserve,
+            destination_reserve,
+            trade_direction,
+        )
+    }
+
+    /// Amount of destination token received for each amount in
+    /// `in_amounts`, after `fees`, every quote starting from the given
+    /// reserves.  Amounts the curve cannot swap, for example because their
+    /// output rounds down to zero, are `None`.
+    pub fn quote(
+        &self,
+        in_amounts: &[u64],
+        source_reserve: u64,
+        destination_reserve: u64,
+        trade_direction: TradeDirection,
+        fees: &Fees,
+    ) -> Vec<Option<u64>> {
+        let swap_curve = self.to_swap_curve();
+        in_amounts
+            .iter()
+            .map(|&amount_in| {
+                let result = swap_curve.swap(
+                    u128::from(amount_in),
+                    u128::from(source_reserve),
+                    u128::from(destination_reserve),
+                    trade_direction,
+                    fees,
+                )?;
+                u64::try_from(result.destination_amount_swapped).ok()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_curve_round_trip() {
+        for curve in [
+            PoolCurve::ConstantProduct,
+            PoolCurve::ConstantPrice { token_b_price: 15 },
+            PoolCurve::Stable { amp: 100 },
+            PoolCurve::Offset {
+                token_b_offset: 1_000_000,
+            },
+        ]
+        .iter()
+        {
+            assert_eq!(
+                PoolCurve::from_swap_curve(&curve.to_swap_curve()).unwrap(),
+                *curve
+            );
+        }
+    }
+
+    #[test]
+    fn stable_beats_constant_product_on_balanced_reserves() {
+        let fees = Fees::default();
+        let in_amounts = [100_000];
+        let constant_product = PoolCurve::ConstantProduct.quote(
+            &in_amounts,
+            1_000_000,
+            1_000_000,
+            TradeDirection::AtoB,
+            &fees,
+        );
+        let stable = PoolCurve::Stable { amp: 100 }.quote(
+            &in_amounts,
+            1_000_000,
+            1_000_000,
+            TradeDirection::AtoB,
+            &fees,
+        );
+        assert!(stable[0].unwrap() > constant_product[0].unwrap());
+    }
+
+    #[test]
+    fn spot_amount_out_of_each_curve() {
+        let curve = PoolCurve::ConstantProduct;
+        assert_eq!(
+            curve.spot_amount_out(1_000, 1_000_000, 2_000_000, TradeDirection::AtoB),
+            Some(2_000)
+        );
+        assert_eq!(
+            curve.spot_amount_out(1_000, 0, 2_000_000, TradeDirection::AtoB),
+            None
+        );
+
+        let curve = PoolCurve::ConstantPrice { token_b_price: 5 };
+        assert_eq!(
+            curve.spot_amount_out(1_000, 1_000_000, 1_000_000, TradeDirection::AtoB),
+            Some(200)
+        );
+        assert_eq!(
+            curve.spot_amount_out(1_000, 1_000_000, 1_000_000, TradeDirection::BtoA),
+            Some(5_000)
+        );
+
+        let curve = PoolCurve::Stable { amp: 100 };
+        assert_eq!(
+            curve.spot_amount_out(1_000, 1_000_000, 1_000_000, TradeDirection::AtoB),
+            Some(1_000)
+        );
+
+        // a fresh offset pool has no token B yet
+        let curve = PoolCurve::Offset {
+            token_b_offset: 1_000_000,
+        };
+        assert_eq!(
+            curve.spot_amount_out(1_000, 1_000_000, 0, TradeDirection::AtoB),
+            Some(1_000)
+        );
+        assert_eq!(
+            curve.spot_amount_out(1_000, 0, 1_000_000, TradeDirection::BtoA),
+            Some(1_000)
+        );
+    }
+
+    #[test]
+    fn offset_adds_to_token_b_reserve() {
+        let fees = Fees::default();
+        let in_amounts = [1_000];
+        let offset = PoolCurve::Offset {
+            token_b_offset: 500_000,
+        }
+        .quote(&in_amounts, 1_000_000, 500_000, TradeDirection::AtoB, &fees);
+        let constant_product = PoolCurve::ConstantProduct.quote(
+            &in_amounts,
+            1_000_000,
+            1_000_000,
+            TradeDirection::AtoB,
+            &fees,
+        );
+        assert_eq!(offset, constant_product);
+    }
+}
@@ -0,0 +1,210 @@
+//! Discovering routes between mints across many pools
+
+use {
+    crate::{
+        pool::PoolSnapshot,
+        route::{find_best_route, Route},
+    },
+    solana_program::pubkey::Pubkey,
+    spl_token_swap::utils::BASIS_POINTS,
+    std::collections::{BTreeMap, BTreeSet},
+};
+
+/// Sequence of routes taking the source mint to the destination mint, where
+/// the output of each leg is the input of the next one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    /// Mints visited, from source to destination
+    pub mints: Vec<Pubkey>,
+    /// One route per consecutive pair of mints
+    pub legs: Vec<Route>,
+}
+
+impl Path {
+    /// Amount of source token going into the first leg
+    pub fn amount_in(&self) -> u64 {
+        self.legs.first().map_or(0, |leg| leg.amount_in)
+    }
+
+    /// Expected amount of destination token coming out of the last leg
+    pub fn expected_amount_out(&self) -> u64 {
+        self.legs.last().map_or(0, |leg| leg.expected_amount_out)
+    }
+
+    /// Price impact of the whole path in basis points, compounding the
+    /// impact of every leg
+    pub fn price_impact_bps(&self) -> Option<u128> {
+        let remaining = self.legs.iter().try_fold(BASIS_POINTS, |remaining, leg| {
+            let kept = BASIS_POINTS.checked_sub(leg.price_impact_bps?)?;
+            remaining.checked_mul(kept)?.checked_div(BASIS_POINTS)
+        })?;
+        BASIS_POINTS.checked_sub(remaining)
+    }
+}
+
+/// Token adjacency graph built from a set of pools, where two mints are
+/// connected if at least one pool trades them against each other
+pub struct RouteGraph {
+    pools: Vec<PoolSnapshot>,
+    adjacency: BTreeMap<Pubkey, BTreeSet<Pubkey>>,
+}
+
+impl RouteGraph {
+    /// Build the graph from all known pools
+    pub fn new(pools: Vec<PoolSnapshot>) -> Self {
+        let mut adjacency: BTreeMap<Pubkey, BTreeSet<Pubkey>> = BTreeMap::new();
+        for pool in pools.iter() {
+            adjacency
+                .entry(pool.token_a_mint)
+                .or_default()
+                .insert(pool.token_b_mint);
+            adjacency
+                .entry(pool.token_b_mint)
+                .or_default()
+                .insert(pool.token_a_mint);
+        }
+        Self { pools, adjacency }
+    }
+
+    /// All pools in the graph
+    pub fn pools(&self) -> &[PoolSnapshot] {
+        &self.pools
+    }
+
+    /// Mints directly tradeable against `mint`
+    pub fn neighbors(&self, mint: &Pubkey) -> Vec<Pubkey> {
+        self.adjacency
+            .get(mint)
+            .map(|neighbors| neighbors.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Find the path from `source_mint` to `destination_mint` giving the
+    /// most destination token, considering the direct pair and every 2-hop
+    /// path through a mint connected to both.  Each leg is split across all
+    /// pools of its pair using `parts` parts.
+    pub fn find_best_path(
+        &self,
+        source_mint: &Pubkey,
+        destination_mint: &Pubkey,
+        amount_in: u64,
+        parts: u64,
+    ) -> Option<Path> {
+        let mut best: Option<Path> = None;
+        let mut consider = |path: Option<Path>| {
+            if let Some(path) = path {
+                let better = best.as_ref().map_or(true, |best| {
+                    path.expected_amount_out() > best.expected_amount_out()
+                });
+                if better {
+                    best = Some(path);
+                }
+            }
+        };
+
+        consider(self.find_path(&[*source_mint, *destination_mint], amount_in, parts));
+        let destination_neighbors = self.adjacency.get(destination_mint);
+        for intermediate_mint in self.neighbors(source_mint) {
+            if intermediate_mint == *destination_mint
+                || !destination_neighbors
+                    .map_or(false, |neighbors| neighbors.contains(&intermediate_mint))
+            {
+                continue;
+            }
+            consider(self.find_path(
+                &[*source_mint, intermediate_mint, *destination_mint],
+                amount_in,
+                parts,
+            ));
+        }
+        best
+    }
+
+    /// Route `amount_in` through the given mints in order, or `None` if a
+    /// leg cannot be routed
+    pub fn find_path(&self, mints: &[Pubkey], amount_in: u64, parts: u64) -> Option<Path> {
+        let mut legs = vec![];
+        let mut leg_amount_in = amount_in;
+        for pair in mints.windows(2) {
+            let leg = find_best_route(&self.pools, &pair[0], &pair[1], leg_amount_in, parts);
+            if leg.splits.is_empty() || leg.expected_amount_out == 0 {
+                return None;
+            }
+            leg_amount_in = leg.expected_amount_out;
+            legs.push(leg);
+        }
+        if legs.is_empty() {
+            return None;
+        }
+        Some(Path {
+            mints: mints.to_vec(),
+            legs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pool::test::constant_product_pool;
+
+    #[test]
+    fn direct_path() {
+        let sol = Pubkey::new_unique();
+        let usdc = Pubkey::new_unique();
+        let graph = RouteGraph::new(vec![constant_product_pool(sol, usdc, 1_000_000, 1_000_000)]);
+        let path = graph.find_best_path(&sol, &usdc, 1_000, 10).unwrap();
+        assert_eq!(path.mints, vec![sol, usdc]);
+        assert_eq!(path.amount_in(), 1_000);
+        assert!(path.expected_amount_out() > 0);
+
+        // fewer tokens than parts
+        let path = graph.find_best_path(&sol, &usdc, 5, 10).unwrap();
+        assert_eq!(path.amount_in(), 5);
+        assert!(path.expected_amount_out() > 0);
+    }
+
+    #[test]
+    fn two_hop_path_without_direct_pool() {
+        let srm = Pubkey::new_unique();
+        let usdc = Pubkey::new_unique();
+        let ray = Pubkey::new_unique();
+        let graph = RouteGraph::new(vec![
+            constant_product_pool(srm, usdc, 1_000_000, 1_000_000),
+            constant_product_pool(ray, usdc, 1_000_000, 1_000_000),
+        ]);
+        let path = graph.find_best_path(&srm, &ray, 1_000, 10).unwrap();
+        assert_eq!(path.mints, vec![srm, usdc, ray]);
+        assert_eq!(path.legs.len(), 2);
+        assert_eq!(path.legs[1].amount_in, path.legs[0].expected_amount_out);
+        let impact = path.price_impact_bps().unwrap();
+        for leg in path.legs.iter() {
+            assert!(impact >= leg.price_impact_bps.unwrap());
+        }
+    }
+
+    #[test]
+    fn two_hop_path_beats_thin_direct_pool() {
+        let srm = Pubkey::new_unique();
+        let usdc = Pubkey::new_unique();
+        let ray = Pubkey::new_unique();
+        let graph = RouteGraph::new(vec![
+            constant_product_pool(srm, ray, 10_000, 10_000),
+            constant_product_pool(srm, usdc, 100_000_000, 100_000_000),
+            constant_product_pool(usdc, ray, 100_000_000, 100_000_000),
+        ]);
+        let path = graph.find_best_path(&srm, &ray, 5_000, 10).unwrap();
+        assert_eq!(path.mints, vec![srm, usdc, ray]);
+    }
+
+    #[test]
+    fn no_path() {
+        let srm = Pubkey::new_unique();
+        let usdc = Pubkey::new_unique();
+        let graph = RouteGraph::new(vec![constant_product_pool(srm, usdc, 1_000_000, 1_000_000)]);
+        assert_eq!(
+            graph.find_best_path(&srm, &Pubkey::new_unique(), 1_000, 10),
+            None
+        );
+    }
+}
@@ -0,0 +1,17 @@
+#![deny(missing_docs)]
+
+//! Off-chain route finding for the oo-route program, pricing pools with the
+//! curves of the token-swap program.
+
+pub mod client;
+pub mod curves;
+pub mod graph;
+pub mod plan;
+pub mod pool;
+pub mod route;
+
+pub use graph::{Path, RouteGraph};
+pub use plan::{HopPlan, PoolPlan, RoutePlan};
+pub use route::{
+    find_best_route, find_best_route_exact_out, find_best_route_with_cost, Route, RouteSplit,
+};
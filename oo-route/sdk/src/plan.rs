@@ -0,0 +1,309 @@
+//! JSON description of a route, for exchanging and auditing routes between
+//! front-ends and bots
+
+use {
+    crate::{graph::Path, route::Route},
+    serde::{Deserialize, Serialize},
+    solana_program::pubkey::Pubkey,
+    spl_token_swap::utils::BASIS_POINTS,
+};
+
+/// Trade through a single pool
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PoolPlan {
+    /// Address of the pool's state account
+    #[serde(with = "pubkey_string")]
+    pub pool: Pubkey,
+    /// Amount of source token sent to the pool
+    pub amount_in: u64,
+    /// Expected amount of destination token received from the pool
+    pub expected_amount_out: u64,
+    /// Least amount of destination token accepted from the pool
+    pub minimum_amount_out: u64,
+}
+
+/// Trade from one mint to the next, split across one or more pools
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HopPlan {
+    /// Mint traded into the pools
+    #[serde(with = "pubkey_string")]
+    pub source_mint: Pubkey,
+    /// Mint received from the pools
+    #[serde(with = "pubkey_string")]
+    pub destination_mint: Pubkey,
+    /// Amount of source token going into the hop
+    pub amount_in: u64,
+    /// Expected amount of destination token coming out of the hop
+    pub expected_amount_out: u64,
+    /// Least amount of destination token accepted from the hop
+    pub minimum_amount_out: u64,
+    /// Pools receiving a share of the input
+    pub pools: Vec<PoolPlan>,
+}
+
+/// Complete route from a source mint to a destination mint, going through
+/// every hop in order
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RoutePlan {
+    /// Mint traded into the first hop
+    #[serde(with = "pubkey_string")]
+    pub source_mint: Pubkey,
+    /// Mint received from the last hop
+    #[serde(with = "pubkey_string")]
+    pub destination_mint: Pubkey,
+    /// Amount of source token going into the route
+    pub amount_in: u64,
+    /// Expected amount of destination token coming out of the route
+    pub expected_amount_out: u64,
+    /// Least amount of destination token accepted from the route
+    pub minimum_amount_out: u64,
+    /// Hops of the route, from source to destination
+    pub hops: Vec<HopPlan>,
+}
+
+impl RoutePlan {
+    /// Describe a single-hop `route`, accepting `slippage_bps` basis points
+    /// less than the expected output of every pool
+    pub fn from_route(
+        route: &Route,
+        source_mint: Pubkey,
+        destination_mint: Pubkey,
+        slippage_bps: u64,
+    ) -> Self {
+        Self::from_hops(
+            vec![HopPlan::from_route(
+                route,
+                source_mint,
+                destination_mint,
+                route.amount_in,
+                slippage_bps,
+            )],
+            route.expected_amount_out,
+        )
+    }
+
+    /// Describe a multi-hop `path`, accepting `slippage_bps` basis points
+    /// less than the expected output of every pool.
+    ///
+    /// Every hop after the first trades the minimum output of the hop before
+    /// it, so it can always be funded, and anything received above that
+    /// minimum is left untraded.  Its pools get their share of that smaller
+    /// input, and their expected outputs shrink in proportion, which never
+    /// overestimates a concave curve.  The slippage therefore compounds:
+    /// the minimum of the route is about `(1 - slippage) ^ hops` of its
+    /// expected output.
+    pub fn from_path(path: &Path, slippage_bps: u64) -> Self {
+        let mut amount_in = path.amount_in();
+        let hops = path
+            .mints
+            .windows(2)
+            .zip(path.legs.iter())
+            .map(|(mints, leg)| {
+                let hop = HopPlan::from_route(leg, mints[0], mints[1], amount_in, slippage_bps);
+                amount_in = hop.minimum_amount_out;
+                hop
+            })
+            .collect();
+        Self::from_hops(hops, path.expected_amount_out())
+    }
+
+    fn from_hops(hops: Vec<HopPlan>, expected_amount_out: u64) -> Self {
+        let first = hops.first();
+        let last = hops.last();
+        Self {
+            source_mint: first.map_or_else(Pubkey::default, |hop| hop.source_mint),
+            destination_mint: last.map_or_else(Pubkey::default, |hop| hop.destination_mint),
+            amount_in: first.map_or(0, |hop| hop.amount_in),
+            expected_amount_out,
+            minimum_amount_out: last.map_or(0, |hop| hop.minimum_amount_out),
+            hops,
+        }
+    }
+
+    /// Serialize the plan as JSON
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    /// Parse a plan from JSON
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+}
+
+impl HopPlan {
+    /// Describe `route` traded with `amount_in` instead of its own input,
+    /// scaling the input and expected output of every pool to match
+    fn from_route(
+        route: &Route,
+        source_mint: Pubkey,
+        destination_mint: Pubkey,
+        amount_in: u64,
+        slippage_bps: u64,
+    ) -> Self {
+        let scale = |amount: u64| {
+            if route.amount_in == 0 {
+                0
+            } else {
+                (u128::from(amount) * u128::from(amount_in) / u128::from(route.amount_in)) as u64
+            }
+        };
+        let mut pools: Vec<PoolPlan> = route
+            .splits
+            .iter()
+            .map(|split| {
+                let expected_amount_out = scale(split.expected_amount_out);
+                PoolPlan {
+                    pool: split.pool,
+                    amount_in: scale(split.amount_in),
+                    expected_amount_out,
+                    minimum_amount_out: minimum_amount_out(expected_amount_out, slippage_bps),
+                }
+            })
+            .collect();
+        // the rounding remainder of the input goes to the last pool
+        let scaled: u64 = pools.iter().map(|pool| pool.amount_in).sum();
+        if let Some(last) = pools.last_mut() {
+            last.amount_in += amount_in - scaled;
+        }
+        Self {
+            source_mint,
+            destination_mint,
+            amount_in,
+            expected_amount_out: pools.iter().map(|pool| pool.expected_amount_out).sum(),
+            minimum_amount_out: pools.iter().map(|pool| pool.minimum_amount_out).sum(),
+            pools,
+        }
+    }
+}
+
+/// Expected amount less `slippage_bps` basis points, rounded down
+fn minimum_amount_out(expected_amount_out: u64, slippage_bps: u64) -> u64 {
+    let kept = BASIS_POINTS.saturating_sub(u128::from(slippage_bps));
+    (u128::from(expected_amount_out) * kept / BASIS_POINTS) as u64
+}
+
+/// Pubkeys as base58 strings, the way wallets and explorers show them
+mod pubkey_string {
+    use {
+        serde::{de::Error, Deserialize, Deserializer, Serializer},
+        solana_program::pubkey::Pubkey,
+        std::str::FromStr,
+    };
+
+    pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(pubkey)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        Pubkey::from_str(&string).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{graph::RouteGraph, pool::test::constant_product_pool, route::find_best_route},
+    };
+
+    #[test]
+    fn route_round_trip() {
+        let token_a_mint = Pubkey::new_unique();
+        let token_b_mint = Pubkey::new_unique();
+        let pools = vec![
+            constant_product_pool(token_a_mint, token_b_mint, 1_000_000, 1_000_000),
+            constant_product_pool(token_a_mint, token_b_mint, 2_000_000, 2_000_000),
+        ];
+        let route = find_best_route(&pools, &token_a_mint, &token_b_mint, 100_000, 10);
+        let plan = RoutePlan::from_route(&route, token_a_mint, token_b_mint, 50);
+        assert_eq!(plan.hops.len(), 1);
+        assert_eq!(plan.hops[0].pools.len(), 2);
+        assert_eq!(plan.amount_in, 100_000);
+        assert_eq!(plan.expected_amount_out, route.expected_amount_out);
+        for (pool, split) in plan.hops[0].pools.iter().zip(route.splits.iter()) {
+            assert_eq!(pool.pool, split.pool);
+            assert_eq!(
+                pool.minimum_amount_out,
+                split.expected_amount_out * 9_950 / 10_000
+            );
+        }
+        assert!(plan.minimum_amount_out <= plan.expected_amount_out);
+
+        let json = plan.to_json().unwrap();
+        assert!(json.contains(&token_a_mint.to_string()));
+        assert_eq!(RoutePlan::from_json(&json).unwrap(), plan);
+    }
+
+    #[test]
+    fn path_round_trip() {
+        let srm = Pubkey::new_unique();
+        let usdc = Pubkey::new_unique();
+        let ray = Pubkey::new_unique();
+        let graph = RouteGraph::new(vec![
+            constant_product_pool(srm, usdc, 1_000_000, 1_000_000),
+            constant_product_pool(ray, usdc, 1_000_000, 1_000_000),
+        ]);
+        let path = graph.find_best_path(&srm, &ray, 1_000, 10).unwrap();
+        let plan = RoutePlan::from_path(&path, 100);
+        assert_eq!(plan.source_mint, srm);
+        assert_eq!(plan.destination_mint, ray);
+        assert_eq!(plan.hops.len(), 2);
+        assert_eq!(plan.hops[0].destination_mint, usdc);
+        assert_eq!(plan.hops[1].source_mint, usdc);
+        assert_eq!(plan.expected_amount_out, path.expected_amount_out());
+        assert_eq!(plan.minimum_amount_out, plan.hops[1].minimum_amount_out);
+
+        // the second hop is funded by what the first one guarantees
+        assert_eq!(plan.hops[1].amount_in, plan.hops[0].minimum_amount_out);
+        let funded: u64 = plan.hops[1].pools.iter().map(|pool| pool.amount_in).sum();
+        assert_eq!(funded, plan.hops[1].amount_in);
+
+        let json = plan.to_json().unwrap();
+        assert_eq!(RoutePlan::from_json(&json).unwrap(), plan);
+    }
+
+    #[test]
+    fn path_minimum_compounds_slippage() {
+        let srm = Pubkey::new_unique();
+        let usdc = Pubkey::new_unique();
+        let ray = Pubkey::new_unique();
+        let graph = RouteGraph::new(vec![
+            constant_product_pool(srm, usdc, 1_000_000_000, 1_000_000_000),
+            constant_product_pool(ray, usdc, 1_000_000_000, 1_000_000_000),
+        ]);
+        let path = graph.find_best_path(&srm, &ray, 1_000_000, 10).unwrap();
+        let plan = RoutePlan::from_path(&path, 100);
+        let expected = path.legs[1].expected_amount_out;
+        // each hop keeps 99% of the one before, up to rounding
+        let compounded = expected * 99 * 99 / (100 * 100);
+        assert!(plan.minimum_amount_out <= compounded);
+        assert!(plan.minimum_amount_out + 2 >= compounded);
+        // while the first hop alone only loses 1%
+        assert_eq!(
+            plan.hops[0].minimum_amount_out,
+            path.legs[0].expected_amount_out * 99 / 100
+        );
+    }
+
+    #[test]
+    fn invalid_json() {
+        assert!(RoutePlan::from_json("{}").is_err());
+
+        let token_a_mint = Pubkey::new_unique();
+        let token_b_mint = Pubkey::new_unique();
+        let pools = vec![constant_product_pool(
+            token_a_mint,
+            token_b_mint,
+            1_000_000,
+            1_000_000,
+        )];
+        let route = find_best_route(&pools, &token_a_mint, &token_b_mint, 1_000, 1);
+        let json = RoutePlan::from_route(&route, token_a_mint, token_b_mint, 0)
+            .to_json()
+            .unwrap()
+            .replace(&token_a_mint.to_string(), "not a pubkey");
+        assert!(RoutePlan::from_json(&json).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! Snapshots of pools used for quoting
+
+use {
+    crate::curves::PoolCurve,
+    solana_program::{program_error::ProgramError, program_pack::Pack, pubkey::Pubkey},
+    spl_token::state::Account,
+    spl_token_swap::{
+        curve::{calculator::TradeDirection, fees::Fees},
+        state::{SwapState, SwapVersion},
+    },
+};
+
+/// State of a pool and its reserves at a point in time
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoolSnapshot {
+    /// Address of the pool's state account
+    pub address: Pubkey,
+    /// Mint of token A
+    pub token_a_mint: Pubkey,
+    /// Mint of token B
+    pub token_b_mint: Pubkey,
+    /// Curve pricing the pool
+    pub curve: PoolCurve,
+    /// Fees charged on every swap
+    pub fees: Fees,
+    /// Balance of the pool's token A account
+    pub token_a_amount: u64,
+    /// Balance of the pool's token B account
+    pub token_b_amount: u64,
+}
+
+impl PoolSnapshot {
+    /// Build a snapshot from token-swap state and its token balances
+    pub fn from_swap(
+        address: Pubkey,
+        swap: &dyn SwapState,
+        token_a_amount: u64,
+        token_b_amount: u64,
+    ) -> Result<Self, ProgramError> {
+        Ok(Self {
+            address,
+            token_a_mint: *swap.token_a_mint(),
+            token_b_mint: *swap.token_b_mint(),
+            curve: PoolCurve::from_swap_curve(swap.swap_curve())?,
+            fees: swap.fees().clone(),
+            token_a_amount,
+            token_b_amount,
+        })
+    }
+
+    /// Build a snapshot from the raw data of a token-swap account and of its
+    /// token A and token B accounts
+    pub fn unpack(
+        address: Pubkey,
+        swap_data: &[u8],
+        token_a_data: &[u8],
+        token_b_data: &[u8],
+    ) -> Result<Self, ProgramError> {
+        let swap = SwapVersion::unpack(swap_data)?;
+        let token_a = Account::unpack(token_a_data)?;
+        let token_b = Account::unpack(token_b_data)?;
+        Self::from_swap(address, swap.as_ref(), token_a.amount, token_b.amount)
+    }
+
+    /// Trade direction, source reserve and destination reserve for a trade
+    /// from `source_mint` into `destination_mint`, or `None` if the pool does
+    /// not trade that pair
+    pub fn reserves(
+        &self,
+        source_mint: &Pubkey,
+        destination_mint: &Pubkey,
+    ) -> Option<(TradeDirection, u64, u64)> {
+        if *source_mint == self.token_a_mint && *destination_mint == self.token_b_mint {
+            Some((
+                TradeDirection::AtoB,
+                self.token_a_amount,
+                self.token_b_amount,
+            ))
+        } else if *source_mint == self.token_b_mint && *destination_mint == self.token_a_mint {
+            Some((
+                TradeDirection::BtoA,
+                self.token_b_amount,
+                self.token_a_amount,
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Output of `amount_in` traded from `source_mint` into
+    /// `destination_mint` at the current price of the pool, before slippage
+    /// and fees
+    pub fn spot_amount_out(
+        &self,
+        source_mint: &Pubkey,
+        destination_mint: &Pubkey,
+        amount_in: u64,
+    ) -> Option<u128> {
+        let (trade_direction, source_amount, destination_amount) =
+            self.reserves(source_mint, destination_mint)?;
+        self.curve.spot_amount_out(
+            amount_in,
+            source_amount,
+            destination_amount,
+            trade_direction,
+        )
+    }
+
+    /// Smallest amount of `source_mint` the pool swaps into at least
+    /// `amount_out` of `destination_mint`, or `None` if the pool does not
+    /// trade that pair or cannot deliver that much
+    pub fn quote_amount_in(
+        &self,
+        source_mint: &Pubkey,
+        destination_mint: &Pubkey,
+        amount_out: u64,
+    ) -> Option<u64> {
+        let (trade_direction, source_amount, destination_amount) =
+            self.reserves(source_mint, destination_mint)?;
+        self.curve.quote_amount_in(
+            amount_out,
+            source_amount,
+            destination_amount,
+            trade_direction,
+            &self.fees,
+        )
+    }
+
+    /// Expected output for each amount in `in_amounts` traded from
+    /// `source_mint` into `destination_mint`, `None` for the amounts the pool
+    /// cannot swap, or `None` overall if the pool does not trade that pair
+    pub fn quote(
+        &self,
+        source_mint: &Pubkey,
+        destination_mint: &Pubkey,
+        in_amounts: &[u64],
+    ) -> Option<Vec<Option<u64>>> {
+        let (trade_direction, source_amount, destination_amount) =
+            self.reserves(source_mint, destination_mint)?;
+        Some(self.curve.quote(
+            in_amounts,
+            source_amount,
+            destination_amount,
+            trade_direction,
+            &self.fees,
+        ))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// Snapshot of a fee-less pool
+    pub fn pool_with_curve(
+        curve: PoolCurve,
+        token_a_mint: Pubkey,
+        token_b_mint: Pubkey,
+        token_a_amount: u64,
+        token_b_amount: u64,
+    ) -> PoolSnapshot {
+        PoolSnapshot {
+            address: Pubkey::new_unique(),
+            token_a_mint,
+            token_b_mint,
+            curve,
+            fees: Fees::default(),
+            token_a_amount,
+            token_b_amount,
+        }
+    }
+
+    /// Snapshot of a fee-less constant product pool
+    pub fn constant_product_pool(
+        token_a_mint: Pubkey,
+        token_b_mint: Pubkey,
+        token_a_amount: u64,
+        token_b_amount: u64,
+    ) -> PoolSnapshot {
+        pool_with_curve(
+            PoolCurve::ConstantProduct,
+            token_a_mint,
+            token_b_mint,
+            token_a_amount,
+            token_b_amount,
+        )
+    }
+}
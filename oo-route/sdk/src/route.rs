@@ -0,0 +1,411 @@
+//! Splitting a trade across pools
+
+use {
+    crate::pool::PoolSnapshot,
+    solana_program::pubkey::Pubkey,
+    spl_token_swap::utils::{
+        distribution_row, find_distribution_with_cost, interpolation, price_impact_bps,
+    },
+};
+
+/// Portion of a route sent through a single pool
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteSplit {
+    /// Address of the swap account
+    pub pool: Pubkey,
+    /// Amount of source token sent to the pool
+    pub amount_in: u64,
+    /// Expected amount of destination token received from the pool
+    pub expected_amount_out: u64,
+    /// Price impact of the split in basis points, against the spot price of
+    /// the pool and including fees
+    pub price_impact_bps: Option<u128>,
+}
+
+/// Best split of an input amount across a set of pools
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Route {
+    /// Total amount of source token
+    pub amount_in: u64,
+    /// Total expected amount of destination token
+    pub expected_amount_out: u64,
+    /// Pools receiving a non-zero share of the input
+    pub splits: Vec<RouteSplit>,
+    /// Price impact of the whole route in basis points, against the spot
+    /// prices of all of its pools
+    pub price_impact_bps: Option<u128>,
+}
+
+/// Find the split of `amount_in` across `pools` giving the most destination
+/// token, with the input divided into `parts` equal parts.
+///
+/// Pools not trading `source_mint` for `destination_mint`, or that cannot
+/// quote any depth of the input, are ignored.  Any rounding remainder of the
+/// input goes to the last split, so the expected output is a lower bound.
+pub fn find_best_route(
+    pools: &[PoolSnapshot],
+    source_mint: &Pubkey,
+    destination_mint: &Pubkey,
+    amount_in: u64,
+    parts: u64,
+) -> Route {
+    find_best_route_with_cost(pools, source_mint, destination_mint, amount_in, parts, 0)
+}
+
+/// Same as `find_best_route`, but every pool used by the route costs
+/// `hop_cost` destination tokens, so small trades stay in a single pool
+/// unless splitting gains more than the extra hop costs.
+pub fn find_best_route_with_cost(
+    pools: &[PoolSnapshot],
+    source_mint: &Pubkey,
+    destination_mint: &Pubkey,
+    amount_in: u64,
+    parts: u64,
+    hop_cost: u64,
+) -> Route {
+    let in_amounts = interpolation(amount_in, parts);
+    let candidates: Vec<(&PoolSnapshot, Vec<i128>)> = pools
+        .iter()
+        .filter_map(|pool| {
+            let quotes = pool.quote(source_mint, destination_mint, in_amounts.as_slice())?;
+            if quotes.iter().all(Option::is_none) {
+                return None;
+            }
+            let matrix = distribution_row(quotes.into_iter().map(|quote| quote.map(u128::from)));
+            Some((pool, matrix))
+        })
+        .collect();
+    let amounts: Vec<&[i128]> = candidates
+        .iter()
+        .map(|(_, matrix)| matrix.as_slice())
+        .collect();
+    let costs = vec![hop_cost as i128; amounts.len()];
+    let distribution = find_distribution_with_cost(parts, amounts.as_slice(), costs.as_slice());
+    // unquotable depths are negative, and only chosen if nothing else fits
+    let reachable = amounts
+        .iter()
+        .zip(distribution.iter())
+        .all(|(matrix, &pool_parts)| matrix[pool_parts as usize] >= 0);
+
+    let (mut splits, spot_amounts_out): (Vec<RouteSplit>, Vec<Option<u128>>) = if reachable {
+        candidates
+            .iter()
+            .zip(distribution.iter())
+            .filter(|(_, pool_parts)| **pool_parts > 0)
+            .map(|((pool, matrix), &pool_parts)| {
+                let amount_in = in_amounts[pool_parts as usize - 1];
+                let expected_amount_out = matrix[pool_parts as usize] as u64;
+                let spot_amount_out =
+                    pool.spot_amount_out(source_mint, destination_mint, amount_in);
+                let split = RouteSplit {
+                    pool: pool.address,
+                    amount_in,
+                    expected_amount_out,
+                    price_impact_bps: spot_amount_out.and_then(|spot_amount_out| {
+                        price_impact_bps(u128::from(expected_amount_out), spot_amount_out)
+                    }),
+                };
+                (split, spot_amount_out)
+            })
+            .unzip()
+    } else {
+        (vec![], vec![])
+    };
+    let routed: u64 = splits.iter().map(|split| split.amount_in).sum();
+    if let Some(last) = splits.last_mut() {
+        last.amount_in += amount_in - routed;
+    }
+
+    let expected_amount_out: u64 = splits.iter().map(|split| split.expected_amount_out).sum();
+    let spot_amount_out = spot_amounts_out
+        .iter()
+        .try_fold(0u128, |total, spot_amount_out| {
+            total.checked_add((*spot_amount_out)?)
+        });
+    Route {
+        amount_in,
+        expected_amount_out,
+        splits,
+        price_impact_bps: spot_amount_out.and_then(|spot_amount_out| {
+            price_impact_bps(u128::from(expected_amount_out), spot_amount_out)
+        }),
+    }
+}
+
+/// Find the smallest input whose best split across `pools`, with the input
+/// divided into `parts` equal parts, is expected to give at least
+/// `amount_out` destination token, or `None` if no input is enough.
+///
+/// The input the best single pool needs for `amount_out` bounds the search,
+/// which then bisects over `find_best_route`, so a split needing less input
+/// than any single pool is found.  The search assumes more input never
+/// routes to less output, which holds up to the rounding of the parts.
+pub fn find_best_route_exact_out(
+    pools: &[PoolSnapshot],
+    source_mint: &Pubkey,
+    destination_mint: &Pubkey,
+    amount_out: u64,
+    parts: u64,
+) -> Option<Route> {
+    if amount_out == 0 {
+        return Some(Route::default());
+    }
+    let is_enough = |amount_in: u64| {
+        find_best_route(pools, source_mint, destination_mint, amount_in, parts).expected_amount_out
+            >= amount_out
+    };
+
+    // only a split may be able to deliver more than every single pool
+    let mut high = pools
+        .iter()
+        .filter_map(|pool| pool.quote_amount_in(source_mint, destination_mint, amount_out))
+        .min()
+        .unwrap_or(1);
+    while !is_enough(high) {
+        if high == u64::MAX {
+            return None;
+        }
+        high = high.saturating_mul(2);
+    }
+    // nothing in gives nothing out, so `low` is never enough
+    let mut low = 0;
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        if is_enough(middle) {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+    Some(find_best_route(
+        pools,
+        source_mint,
+        destination_mint,
+        high,
+        parts,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        curves::PoolCurve,
+        pool::test::{constant_product_pool, pool_with_curve},
+    };
+
+    #[test]
+    fn single_pool_takes_everything() {
+        let token_a_mint = Pubkey::new_unique();
+        let token_b_mint = Pubkey::new_unique();
+        let pools = vec![constant_product_pool(
+            token_a_mint,
+            token_b_mint,
+            1_000_000,
+            1_000_000,
+        )];
+        let route = find_best_route(&pools, &token_a_mint, &token_b_mint, 1_000, 10);
+        assert_eq!(route.splits.len(), 1);
+        assert_eq!(route.splits[0].pool, pools[0].address);
+        assert_eq!(route.splits[0].amount_in, 1_000);
+        assert!(route.expected_amount_out > 0);
+    }
+
+    #[test]
+    fn splits_cover_whole_input() {
+        let token_a_mint = Pubkey::new_unique();
+        let token_b_mint = Pubkey::new_unique();
+        let pools = vec![
+            constant_product_pool(token_a_mint, token_b_mint, 1_000_000, 2_000_000),
+            constant_product_pool(token_a_mint, token_b_mint, 3_000_000, 5_000_000),
+        ];
+        let route = find_best_route(&pools, &token_a_mint, &token_b_mint, 5_003, 10);
+        let routed: u64 = route.splits.iter().map(|split| split.amount_in).sum();
+        assert_eq!(routed, 5_003);
+        assert_eq!(
+            route.expected_amount_out,
+            route
+                .splits
+                .iter()
+                .map(|split| split.expected_amount_out)
+                .sum::<u64>()
+        );
+    }
+
+    #[test]
+    fn identical_pools_split_evenly() {
+        let token_a_mint = Pubkey::new_unique();
+        let token_b_mint = Pubkey::new_unique();
+        let pools = vec![
+            constant_product_pool(token_a_mint, token_b_mint, 1_000_000_000, 1_000_000_000),
+            constant_product_pool(token_a_mint, token_b_mint, 1_000_000_000, 1_000_000_000),
+        ];
+        let route = find_best_route(&pools, &token_a_mint, &token_b_mint, 100_000_000, 10);
+        assert_eq!(route.splits.len(), 2);
+        assert_eq!(route.splits[0].amount_in, 50_000_000);
+        assert_eq!(route.splits[1].amount_in, 50_000_000);
+    }
+
+    #[test]
+    fn stable_pool_preferred_for_pegged_pair() {
+        let usdc = Pubkey::new_unique();
+        let usdt = Pubkey::new_unique();
+        let pools = vec![
+            constant_product_pool(usdc, usdt, 1_000_000_000, 1_000_000_000),
+            pool_with_curve(
+                PoolCurve::Stable { amp: 100 },
+                usdc,
+                usdt,
+                1_000_000_000,
+                1_000_000_000,
+            ),
+        ];
+        let route = find_best_route(&pools, &usdc, &usdt, 100_000_000, 10);
+        let stable_split = route
+            .splits
+            .iter()
+            .find(|split| split.pool == pools[1].address)
+            .unwrap();
+        assert!(stable_split.amount_in > 50_000_000);
+    }
+
+    #[test]
+    fn hop_cost_keeps_small_trade_in_one_pool() {
+        let token_a_mint = Pubkey::new_unique();
+        let token_b_mint = Pubkey::new_unique();
+        let pools = vec![
+            constant_product_pool(token_a_mint, token_b_mint, 1_000_000, 1_000_000),
+            constant_product_pool(token_a_mint, token_b_mint, 1_000_000, 1_000_000),
+        ];
+        let route =
+            find_best_route_with_cost(&pools, &token_a_mint, &token_b_mint, 1_000, 10, 1_000);
+        assert_eq!(route.splits.len(), 1);
+        assert_eq!(route.splits[0].amount_in, 1_000);
+    }
+
+    #[test]
+    fn price_impact_of_splits_and_route() {
+        let token_a_mint = Pubkey::new_unique();
+        let token_b_mint = Pubkey::new_unique();
+        let pools = vec![constant_product_pool(
+            token_a_mint,
+            token_b_mint,
+            1_000_000,
+            1_000_000,
+        )];
+        let route = find_best_route(&pools, &token_a_mint, &token_b_mint, 10_000, 1);
+        // x * y = k gives an impact of roughly amount_in / reserve
+        let impact = route.price_impact_bps.unwrap();
+        assert!((99..=100).contains(&impact));
+        assert_eq!(route.splits[0].price_impact_bps, Some(impact));
+
+        // the same trade barely moves a balanced stable pool
+        let pools = vec![pool_with_curve(
+            PoolCurve::Stable { amp: 100 },
+            token_a_mint,
+            token_b_mint,
+            1_000_000,
+            1_000_000,
+        )];
+        let route = find_best_route(&pools, &token_a_mint, &token_b_mint, 10_000, 1);
+        assert!(route.price_impact_bps.unwrap() < 10);
+
+        // splitting across two pools lowers the aggregate impact
+        let pools = vec![
+            constant_product_pool(token_a_mint, token_b_mint, 1_000_000, 1_000_000),
+            constant_product_pool(token_a_mint, token_b_mint, 1_000_000, 1_000_000),
+        ];
+        let route = find_best_route(&pools, &token_a_mint, &token_b_mint, 10_000, 10);
+        assert_eq!(route.splits.len(), 2);
+        assert!(route.price_impact_bps.unwrap() < impact);
+    }
+
+    #[test]
+    fn exact_out_splits_required_input() {
+        let token_a_mint = Pubkey::new_unique();
+        let token_b_mint = Pubkey::new_unique();
+        let pools = vec![
+            constant_product_pool(token_a_mint, token_b_mint, 1_000_000, 1_000_000),
+            constant_product_pool(token_a_mint, token_b_mint, 1_000_000, 1_000_000),
+        ];
+        let single_pool_amount_in = pools[0]
+            .quote_amount_in(&token_a_mint, &token_b_mint, 150_000)
+            .unwrap();
+        let route =
+            find_best_route_exact_out(&pools, &token_a_mint, &token_b_mint, 150_000, 10).unwrap();
+        assert_eq!(route.splits.len(), 2);
+        assert!(route.expected_amount_out >= 150_000);
+        // splitting needs less than sending everything through one pool
+        assert!(route.amount_in < single_pool_amount_in);
+        // and one token less is not enough
+        let smaller = find_best_route(
+            &pools,
+            &token_a_mint,
+            &token_b_mint,
+            route.amount_in - 1,
+            10,
+        );
+        assert!(smaller.expected_amount_out < 150_000);
+
+        // more than either pool holds, but not more than both
+        assert_eq!(
+            pools[0].quote_amount_in(&token_a_mint, &token_b_mint, 1_500_000),
+            None
+        );
+        let route =
+            find_best_route_exact_out(&pools, &token_a_mint, &token_b_mint, 1_500_000, 10).unwrap();
+        assert_eq!(route.splits.len(), 2);
+        assert!(route.expected_amount_out >= 1_500_000);
+
+        // more than both pools hold
+        assert_eq!(
+            find_best_route_exact_out(&pools, &token_a_mint, &token_b_mint, 2_000_000, 10),
+            None
+        );
+    }
+
+    #[test]
+    fn fewer_tokens_than_parts() {
+        let token_a_mint = Pubkey::new_unique();
+        let token_b_mint = Pubkey::new_unique();
+        let pools = vec![
+            constant_product_pool(token_a_mint, token_b_mint, 1_000_000, 1_000_000),
+            constant_product_pool(token_a_mint, token_b_mint, 1_000_000, 1_000_000),
+        ];
+        // the first depths are 0 and 1 token, which give nothing
+        let route = find_best_route(&pools, &token_a_mint, &token_b_mint, 5, 10);
+        assert_eq!(route.amount_in, 5);
+        assert!(route.expected_amount_out > 0);
+        let routed: u64 = route.splits.iter().map(|split| split.amount_in).sum();
+        assert_eq!(routed, 5);
+    }
+
+    #[test]
+    fn nothing_to_route_when_output_rounds_to_zero() {
+        let token_a_mint = Pubkey::new_unique();
+        let token_b_mint = Pubkey::new_unique();
+        let pools = vec![constant_product_pool(
+            token_a_mint,
+            token_b_mint,
+            1_000_000,
+            1_000,
+        )];
+        let route = find_best_route(&pools, &token_a_mint, &token_b_mint, 10, 10);
+        assert!(route.splits.is_empty());
+        assert_eq!(route.expected_amount_out, 0);
+    }
+
+    #[test]
+    fn ignores_pools_for_other_pairs() {
+        let token_a_mint = Pubkey::new_unique();
+        let token_b_mint = Pubkey::new_unique();
+        let pools = vec![
+            constant_product_pool(Pubkey::new_unique(), token_b_mint, 1_000_000, 1_000_000),
+            constant_product_pool(token_b_mint, token_a_mint, 1_000_000, 1_000_000),
+        ];
+        let route = find_best_route(&pools, &token_a_mint, &token_b_mint, 100, 4);
+        assert_eq!(route.splits.len(), 1);
+        assert_eq!(route.splits[0].pool, pools[1].address);
+        assert_eq!(route.amount_in, 100);
+    }
+}
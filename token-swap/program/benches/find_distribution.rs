@@ -0,0 +1,46 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use spl_token_swap::utils::{distribution_buffer_lens, find_distribution, find_distribution_into};
+
+/// Concave output curve, like the quotes of a constant product pool
+fn quotes(partition: u64, depth: i128) -> Vec<i128> {
+    (0..=partition as i128)
+        .map(|k| depth * k * 1_000_000 / (depth + k * 1_000))
+        .collect()
+}
+
+fn bench_find_distribution(c: &mut Criterion) {
+    let mut group = c.benchmark_group("find_distribution");
+    for &(dex_count, partition) in &[(2, 10), (4, 50), (8, 100)] {
+        let rows: Vec<Vec<i128>> = (0..dex_count)
+            .map(|i| quotes(partition, (i as i128 + 1) * 10_000))
+            .collect();
+        let amounts: Vec<&[i128]> = rows.iter().map(|row| row.as_slice()).collect();
+        let id = format!("{}x{}", dex_count, partition);
+
+        group.bench_with_input(BenchmarkId::new("alloc", &id), &amounts, |b, amounts| {
+            b.iter(|| find_distribution(black_box(partition), black_box(amounts)))
+        });
+
+        let (answer_len, parent_len) = distribution_buffer_lens(partition, dex_count);
+        let mut answer = vec![0i128; answer_len];
+        let mut parent = vec![0u64; parent_len];
+        let mut distribution = vec![0u64; dex_count];
+        let costs = vec![0i128; dex_count];
+        group.bench_with_input(BenchmarkId::new("into", &id), &amounts, |b, amounts| {
+            b.iter(|| {
+                find_distribution_into(
+                    black_box(partition),
+                    black_box(amounts),
+                    &costs,
+                    &mut answer,
+                    &mut parent,
+                    &mut distribution,
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_find_distribution);
+criterion_main!(benches);
@@ -141,6 +141,43 @@ fn compute_new_destination_amount(
     u128::try_from(y).ok()
 }
 
+impl StableCurve {
+    /// Amount of destination token received for `source_amount` at the
+    /// marginal price of the curve, ie. before any slippage or fees
+    ///
+    /// The price is the slope of the invariant at the current amounts:
+    /// dy / dx = y * (4 * leverage * x**2 * y + D**3) / (x * (4 * leverage * x * y**2 + D**3))
+    pub fn spot_amount_out(
+        &self,
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+    ) -> Option<u128> {
+        let leverage = self.amp.checked_mul(N_COINS as u64)?;
+        let d_val: U256 = compute_d(leverage, swap_source_amount, swap_destination_amount)?.into();
+        let x: U256 = swap_source_amount.into();
+        let y: U256 = swap_destination_amount.into();
+
+        let d_cubed = checked_u8_power(&d_val, N_COINS.checked_add(1)?)?;
+        let leverage_product = checked_u8_mul(&U256::from(leverage), N_COINS_SQUARED)?
+            .checked_mul(x)?
+            .checked_mul(y)?;
+        let numerator = leverage_product.checked_mul(x)?.checked_add(d_cubed)?;
+        let denominator = leverage_product.checked_mul(y)?.checked_add(d_cubed)?;
+
+        // only the ratio matters, keep the final product within 256 bits
+        let shift = numerator.bits().max(denominator.bits()).saturating_sub(128);
+        let numerator = numerator >> shift;
+        let denominator = denominator >> shift;
+
+        let amount = U256::from(source_amount)
+            .checked_mul(y)?
+            .checked_mul(numerator)?
+            .checked_div(x.checked_mul(denominator)?)?;
+        u128::try_from(amount).ok()
+    }
+}
+
 impl CurveCalculator for StableCurve {
     /// Stable curve
     fn swap_without_fees(
@@ -302,6 +339,39 @@ mod tests {
         assert!(results.is_none());
     }
 
+    #[test]
+    fn spot_amount_out() {
+        let calculator = StableCurve { amp: 100 };
+        assert_eq!(
+            calculator.spot_amount_out(1_000, 1_000_000, 1_000_000),
+            Some(1_000)
+        );
+
+        for &(amp, swap_source_amount, swap_destination_amount) in &[
+            (100, 1_000_000_000, 3_000_000_000),
+            (10, 5_000_000_000_000, 1_000_000_000_000),
+            (1, 1_000_000_000, 4_000_000_000),
+        ] {
+            let calculator = StableCurve { amp };
+            let source_amount = swap_source_amount / 100_000;
+            let spot = calculator
+                .spot_amount_out(source_amount, swap_source_amount, swap_destination_amount)
+                .unwrap();
+            let swapped = calculator
+                .swap_without_fees(
+                    source_amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    TradeDirection::AtoB,
+                )
+                .unwrap()
+                .destination_amount_swapped;
+            // a small trade barely moves the price
+            let diff = (spot as i128 - swapped as i128).abs();
+            assert!(diff <= (spot / 10_000) as i128 + 1);
+        }
+    }
+
     proptest! {
         #[test]
         fn constant_product_swap_no_fee(
@@ -8,7 +8,7 @@ pub mod error;
 pub mod instruction;
 pub mod processor;
 pub mod state;
-mod utils;
+pub mod utils;
 
 #[cfg(not(feature = "no-entrypoint"))]
 mod entrypoint;
@@ -4,7 +4,8 @@ use crate::constraints::{SwapConstraints, SWAP_CONSTRAINTS};
 use crate::instruction::CalculateSwapReturn;
 use crate::state::CalculateSwapReturnRes;
 use crate::utils::{
-    calculate_swap_return, find_distribution, get_real_out_amount, interpolation, to_u128,
+    calculate_swap_return, distribution_row, find_distribution, get_real_out_amount, interpolation,
+    to_u128,
 };
 use crate::{
     curve::{
@@ -497,7 +498,7 @@ impl Processor {
         program_id: &Pubkey,
         amount_in: u64,
         partition: u64,
-        flags: u64,
+        _flags: u64,
         accounts: &[AccountInfo],
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
@@ -539,26 +540,25 @@ impl Processor {
 
         let in_amounts = interpolation(amount_in, partition);
         let res = calculate_swap_return(
-            token_swap,
+            token_swap.as_ref(),
             in_amounts.as_slice(),
             source_account.amount,
             dest_account.amount,
             trade_direction,
         );
         msg!("res length:{}", res.len());
-        let mut matrix: Vec<i128> = res
-            .iter()
-            .map(|item| item.destination_amount_swapped as i128)
-            .collect();
-        let l = matrix.len();
-        if l < (partition + 1) as usize {
-            for _ in l..(partition + 1) as usize {
-                matrix.push(0);
-            }
-        }
+        let matrix = distribution_row(
+            res.iter()
+                .map(|item| item.as_ref().map(|item| item.destination_amount_swapped)),
+        );
         msg!("matrix:{:?}", matrix.as_slice());
-        let distribution = find_distribution(partition, &[matrix.as_slice()]);
-        let out_amount = get_real_out_amount(distribution.as_slice(), matrix.as_slice());
+        let amounts = [matrix.as_slice()];
+        let distribution = find_distribution(partition, &amounts);
+        let out_amount = get_real_out_amount(distribution.as_slice(), &amounts);
+        // only reached through a depth the curve cannot swap
+        if out_amount < 0 {
+            return Err(SwapError::ZeroTradingTokens.into());
+        }
         let obj = CalculateSwapReturnRes {
             distribution: distribution[0],
             out_amount,
@@ -1262,7 +1262,11 @@ mod tests {
             withdraw_all_token_types, withdraw_single_token_type_exact_amount_out,
         },
     };
-    use solana_program::{instruction::Instruction, program_stubs, rent::Rent};
+    use solana_program::{
+        instruction::{AccountMeta, Instruction},
+        program_stubs,
+        rent::Rent,
+    };
     use solana_sdk::account::{create_account_for_test, create_is_signer_account_infos, Account};
     use spl_token::{
         error::TokenError,
@@ -1595,6 +1599,49 @@ mod tests {
             Ok(())
         }
 
+        pub fn calculate_swap_return(
+            &mut self,
+            swap_source_key: &Pubkey,
+            swap_destination_key: &Pubkey,
+            amount_in: u64,
+            partition: u64,
+        ) -> Result<CalculateSwapReturnRes, ProgramError> {
+            let res_key = Pubkey::new_unique();
+            let mut res_account = Account::new(
+                0,
+                CalculateSwapReturnRes::get_packed_len(),
+                &SWAP_PROGRAM_ID,
+            );
+            let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+            let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
+            do_process_instruction(
+                Instruction {
+                    program_id: SWAP_PROGRAM_ID,
+                    accounts: vec![
+                        AccountMeta::new_readonly(self.swap_key, false),
+                        AccountMeta::new_readonly(*swap_source_key, false),
+                        AccountMeta::new_readonly(*swap_destination_key, false),
+                        AccountMeta::new(res_key, false),
+                    ],
+                    data: SwapInstruction::CalculateSwapReturn(CalculateSwapReturn {
+                        amount_in,
+                        partition,
+                        flags: 0,
+                    })
+                    .pack(),
+                },
+                vec![
+                    &mut self.swap_account,
+                    &mut swap_source_account,
+                    &mut swap_destination_account,
+                    &mut res_account,
+                ],
+            )?;
+
+            CalculateSwapReturnRes::unpack_from_slice(&res_account.data)
+        }
+
         #[allow(clippy::too_many_arguments)]
         pub fn deposit_all_token_types(
             &mut self,
@@ -6814,4 +6861,99 @@ mod tests {
             spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
         assert_eq!(swap_token_b.amount, 0);
     }
+
+    #[test]
+    fn test_calculate_swap_return() {
+        let user_key = Pubkey::new_unique();
+        let fees = Fees {
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            ..Fees::default()
+        };
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve),
+        };
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 2_000_000;
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            fees.clone(),
+            swap_curve.clone(),
+            token_a_amount,
+            token_b_amount,
+        );
+        accounts.initialize_swap().unwrap();
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let amount_in = 10_000;
+
+        // a single pool takes every part, for the output of the whole amount
+        for (swap_source_key, swap_destination_key, trade_direction, source_amount, dest_amount) in
+            &[
+                (
+                    swap_token_a_key,
+                    swap_token_b_key,
+                    TradeDirection::AtoB,
+                    token_a_amount,
+                    token_b_amount,
+                ),
+                (
+                    swap_token_b_key,
+                    swap_token_a_key,
+                    TradeDirection::BtoA,
+                    token_b_amount,
+                    token_a_amount,
+                ),
+            ]
+        {
+            let expected = swap_curve
+                .swap(
+                    amount_in as u128,
+                    *source_amount as u128,
+                    *dest_amount as u128,
+                    *trade_direction,
+                    &fees,
+                )
+                .unwrap();
+            assert_eq!(
+                accounts
+                    .calculate_swap_return(swap_source_key, swap_destination_key, amount_in, 10)
+                    .unwrap(),
+                CalculateSwapReturnRes {
+                    distribution: 10,
+                    out_amount: expected.destination_amount_swapped as i128,
+                }
+            );
+        }
+
+        // fewer tokens than parts, the first depths give nothing
+        let expected = swap_curve
+            .swap(
+                5,
+                token_a_amount as u128,
+                token_b_amount as u128,
+                TradeDirection::AtoB,
+                &fees,
+            )
+            .unwrap();
+        assert_eq!(
+            accounts
+                .calculate_swap_return(&swap_token_a_key, &swap_token_b_key, 5, 10)
+                .unwrap(),
+            CalculateSwapReturnRes {
+                distribution: 10,
+                out_amount: expected.destination_amount_swapped as i128,
+            }
+        );
+
+        assert_eq!(
+            accounts.calculate_swap_return(&swap_token_a_key, &swap_token_b_key, 1, 1),
+            Err(SwapError::ZeroTradingTokens.into())
+        );
+        assert_eq!(
+            accounts.calculate_swap_return(&swap_token_a_key, &swap_token_a_key, amount_in, 10),
+            Err(SwapError::InvalidInput.into())
+        );
+    }
 }
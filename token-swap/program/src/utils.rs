@@ -1,107 +1,338 @@
-use crate::curve::base::SwapResult;
+//! Helpers for quoting a swap at several depths and splitting an input
+//! amount across pools
+
+use crate::curve::base::{SwapCurve, SwapResult};
 use crate::curve::calculator::TradeDirection;
+use crate::curve::fees::Fees;
 use crate::error::SwapError;
 use crate::state::SwapState;
 use std::convert::TryInto;
 
-pub const MIN_VALUE: i128 = (-1 as i128) * ((10 as i128).pow(36));
+/// Basis points in one whole
+pub const BASIS_POINTS: u128 = 10_000;
+
+/// Sentinel used by `find_distribution` for unreachable states
+pub const MIN_VALUE: i128 = -(10_i128.pow(36));
 
+/// Quote the swap curve of `token_swap` for every amount in `in_amounts`,
+/// given the current source and destination reserves of the pool.
+///
+/// The amounts are cumulative depths (see `interpolation`), each of which
+/// would be executed as a single swap, so every quote starts from the same
+/// reserves rather than from the reserves left by the previous depth.  A
+/// depth the curve cannot swap, for example because its output rounds down
+/// to zero, is `None` without affecting the other depths.
 pub fn calculate_swap_return(
-    token_swap: Box<dyn SwapState>,
+    token_swap: &dyn SwapState,
     in_amounts: &[u64],
-    mut source_account_amount: u64,
-    mut dest_account_amount: u64,
+    source_account_amount: u64,
+    dest_account_amount: u64,
     trade_direction: TradeDirection,
-) -> Vec<SwapResult> {
-    let result = in_amounts
-        .into_iter()
+) -> Vec<Option<SwapResult>> {
+    in_amounts
+        .iter()
         .map(|&amount_in| {
-            let res = token_swap
-                .swap_curve()
-                .swap(
-                    to_u128(amount_in).unwrap(),
-                    to_u128(source_account_amount).unwrap(),
-                    to_u128(dest_account_amount).unwrap(),
-                    trade_direction,
-                    token_swap.fees(),
-                )
-                .ok_or(SwapError::ZeroTradingTokens)
-                .unwrap();
-            source_account_amount -= amount_in;
-            dest_account_amount += amount_in;
-            res
+            token_swap.swap_curve().swap(
+                u128::from(amount_in),
+                u128::from(source_account_amount),
+                u128::from(dest_account_amount),
+                trade_direction,
+                token_swap.fees(),
+            )
         })
-        .collect();
-    result
+        .collect()
+}
+
+/// Row of `find_distribution` for a dex whose output at every depth is
+/// `outputs`, starting with the output for zero parts.
+///
+/// Depths without an output are set to `MIN_VALUE`, so the search never
+/// picks them but can still give the dex the depths it can quote.
+pub fn distribution_row<I>(outputs: I) -> Vec<i128>
+where
+    I: IntoIterator<Item = Option<u128>>,
+{
+    std::iter::once(0)
+        .chain(outputs.into_iter().map(|output| {
+            output
+                .and_then(|output| output.try_into().ok())
+                .unwrap_or(MIN_VALUE)
+        }))
+        .collect()
+}
+
+/// Smallest amount of source token for which the swap curve of `token_swap`
+/// gives at least `amount_out` destination tokens, given the current source
+/// and destination reserves of the pool.
+///
+/// Rather than inverting each curve, this searches the forward quote, which
+/// never decreases as the amount in grows, so the result always agrees with
+/// `calculate_swap_return` and works for every curve and fee.  Returns `None`
+/// if no amount up to `u64::MAX` is enough.
+pub fn calculate_swap_amount_in(
+    token_swap: &dyn SwapState,
+    amount_out: u64,
+    source_account_amount: u64,
+    dest_account_amount: u64,
+    trade_direction: TradeDirection,
+) -> Option<u64> {
+    swap_curve_amount_in(
+        token_swap.swap_curve(),
+        token_swap.fees(),
+        amount_out,
+        source_account_amount,
+        dest_account_amount,
+        trade_direction,
+    )
+}
+
+/// Same as `calculate_swap_amount_in`, for a swap curve and fees that are not
+/// stored in a token swap, such as those of an off-chain pool snapshot
+pub fn swap_curve_amount_in(
+    swap_curve: &SwapCurve,
+    fees: &Fees,
+    amount_out: u64,
+    source_account_amount: u64,
+    dest_account_amount: u64,
+    trade_direction: TradeDirection,
+) -> Option<u64> {
+    if amount_out == 0 {
+        return Some(0);
+    }
+    let is_enough = |amount_in: u64| {
+        swap_curve
+            .swap(
+                u128::from(amount_in),
+                u128::from(source_account_amount),
+                u128::from(dest_account_amount),
+                trade_direction,
+                fees,
+            )
+            .map_or(false, |result| {
+                result.destination_amount_swapped >= u128::from(amount_out)
+            })
+    };
+
+    let mut high = 1u64;
+    while !is_enough(high) {
+        if high == u64::MAX {
+            return None;
+        }
+        high = high.saturating_mul(2);
+    }
+    // `low` is never enough, `high` always is
+    let mut low = high / 2;
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        if is_enough(middle) {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+    Some(high)
 }
 
-pub fn get_real_out_amount(distribution: &[u64], matrix: &[i128]) -> i128 {
-    distribution.iter().map(|&item| matrix[item as usize]).sum()
+/// Total output of a distribution, where `distribution[i]` is the number of
+/// parts given to the dex whose quotes are `amounts[i]`
+pub fn get_real_out_amount(distribution: &[u64], amounts: &[&[i128]]) -> i128 {
+    distribution
+        .iter()
+        .zip(amounts.iter())
+        .map(|(&parts, matrix)| matrix[parts as usize])
+        .sum()
 }
 
-// 将要兑换的数量 分成不同的深度
+/// Split `in_amount` into `partition` cumulative depths, ie. the i-th entry
+/// is `in_amount * (i + 1) / partition`
 pub fn interpolation(in_amount: u64, partition: u64) -> Vec<u64> {
+    // the product can exceed u64, but every depth is at most `in_amount`
+    let in_amount = u128::from(in_amount);
+    let partition_wide = u128::from(partition);
     (0..partition)
-        .into_iter()
-        .map(|i| {
-            in_amount
-                .checked_mul(i + 1)
-                .expect("in_amount * i failed")
-                .checked_div(partition)
-                .unwrap()
-        })
+        .map(|i| (in_amount * u128::from(i + 1) / partition_wide) as u64)
         .collect::<Vec<u64>>()
 }
 
+/// Find how many of the `partition` parts to send to each dex to maximize
+/// the total output.
+///
+/// `amounts[i][k]` is the output of dex `i` when given `k` parts, so every
+/// row must have `partition + 1` entries with `amounts[i][0] == 0`.  The
+/// result has one entry per dex, in the same order as `amounts`.
 pub fn find_distribution(partition: u64, amounts: &[&[i128]]) -> Vec<u64> {
-    let dex_count = amounts.len();
+    find_distribution_with_cost(partition, amounts, &vec![0; amounts.len()])
+}
 
-    let mut answer: Vec<Vec<i128>> = (0..dex_count)
-        .into_iter()
-        .map(|_| vec![0i128; (partition + 1) as usize])
-        .collect();
+/// Same as `find_distribution`, but every dex receiving at least one part
+/// costs `costs[i]`, expressed in destination tokens.
+///
+/// The cost models the fixed overhead of an extra hop (accounts, compute,
+/// fees), so a split is only chosen when it gains more than it costs.
+///
+/// Panics if a row of `amounts` has fewer than `partition + 1` entries.
+pub fn find_distribution_with_cost(
+    partition: u64,
+    amounts: &[&[i128]],
+    costs: &[i128],
+) -> Vec<u64> {
+    let (answer_len, parent_len) = distribution_buffer_lens(partition, amounts.len());
+    let mut answer = vec![0i128; answer_len];
+    let mut parent = vec![0u64; parent_len];
+    let mut distribution = vec![0u64; amounts.len()];
+    find_distribution_into(
+        partition,
+        amounts,
+        costs,
+        &mut answer,
+        &mut parent,
+        &mut distribution,
+    )
+    .unwrap();
+    distribution
+}
 
-    let mut parent: Vec<Vec<u64>> = (0..dex_count)
-        .into_iter()
-        .map(|_| vec![0u64; (partition + 1) as usize])
-        .collect();
+/// Lengths of the `answer` and `parent` buffers needed by
+/// `find_distribution_into`
+pub fn distribution_buffer_lens(partition: u64, dex_count: usize) -> (usize, usize) {
+    let width = partition as usize + 1;
+    (2 * width, dex_count * width)
+}
 
-    for j in 0usize..partition as usize {
-        answer[0][j] = amounts[0][j];
-        for i in 1..dex_count {
-            answer[i][j] = MIN_VALUE
-        }
-        parent[0][j] = 0;
+/// Allocation-free version of `find_distribution_with_cost`, writing the
+/// parts of every dex into `distribution`.
+///
+/// `answer` and `parent` are scratch space, sized with
+/// `distribution_buffer_lens`, so the search can run on-chain with buffers
+/// allocated once.  All sums saturate instead of overflowing.
+///
+/// When both the best outputs of the previous dexes and the quotes of the
+/// next one are concave, as with the quotes of real pools, the best number
+/// of parts for that dex grows by at most one with every extra part, so
+/// only two candidates are checked instead of all of them.
+pub fn find_distribution_into(
+    partition: u64,
+    amounts: &[&[i128]],
+    costs: &[i128],
+    answer: &mut [i128],
+    parent: &mut [u64],
+    distribution: &mut [u64],
+) -> Result<(), SwapError> {
+    let dex_count = amounts.len();
+    let width = partition as usize + 1;
+    let (answer_len, parent_len) = distribution_buffer_lens(partition, dex_count);
+    if costs.len() != dex_count
+        || distribution.len() != dex_count
+        || answer.len() < answer_len
+        || parent.len() < parent_len
+        || amounts.iter().any(|row| row.len() < width)
+    {
+        return Err(SwapError::InvalidInput);
+    }
+    if dex_count == 0 {
+        return Ok(());
+    }
+
+    // best output of the dexes seen so far for j parts, and the same for
+    // the current dex
+    let (previous, rest) = answer.split_at_mut(width);
+    let current = &mut rest[..width];
+
+    for j in 0..width {
+        previous[j] = if j == 0 {
+            amounts[0][0]
+        } else {
+            amounts[0][j].saturating_sub(costs[0])
+        };
+        parent[j] = 0;
     }
     for i in 1..dex_count {
-        for j in 0usize..partition as usize {
-            answer[i][j] = answer[i - 1][j];
-            parent[i][j] = j as u64;
-            for k in 1usize..j + 1 {
-                if answer[i - 1][j - k] + amounts[i][k] > answer[i][j] {
-                    answer[i][j] = answer[i - 1][j - k] + amounts[i][k];
-                    parent[i][j] = (j - k) as u64;
+        let row = amounts[i];
+        let cost = costs[i];
+        let parent_row = &mut parent[i * width..(i + 1) * width];
+        let concave = is_concave(previous) && is_concave(&row[1..width]);
+
+        // parts given to the current dex in the best split of j - 1 parts
+        let mut last_parts = 0;
+        for j in 0..width {
+            let mut best = previous[j];
+            let mut best_parent = j;
+            let value_of = |k: usize| previous[j - k].saturating_add(row[k]).saturating_sub(cost);
+            if concave {
+                if j > 0 {
+                    let mut parts = last_parts.max(1);
+                    if parts < j && value_of(parts + 1) > value_of(parts) {
+                        parts += 1;
+                    }
+                    last_parts = parts;
+                    let value = value_of(parts);
+                    if value > best {
+                        best = value;
+                        best_parent = j - parts;
+                    }
+                }
+            } else {
+                for k in 1..=j {
+                    let value = value_of(k);
+                    if value > best {
+                        best = value;
+                        best_parent = j - k;
+                    }
                 }
             }
+            current[j] = best;
+            parent_row[j] = best_parent as u64;
         }
+        previous.copy_from_slice(current);
     }
-    let mut distribution: Vec<u64> = vec![];
+
     let mut left = partition as usize;
-    let mut dex = dex_count - 1;
-    loop {
-        if left <= 0 {
-            break;
-        }
-        distribution.push(left as u64 - parent[dex][left]);
-        left = parent[dex][left] as usize;
-        if dex == 0 {
-            break;
-        }
-        dex -= 1;
+    for dex in (0..dex_count).rev() {
+        let previous = parent[dex * width + left] as usize;
+        distribution[dex] = (left - previous) as u64;
+        left = previous;
     }
-    distribution
+    Ok(())
+}
+
+/// Whether every step of `values` gains no more than the step before it
+fn is_concave(values: &[i128]) -> bool {
+    values
+        .windows(3)
+        .all(|window| window[1].saturating_sub(window[0]) >= window[2].saturating_sub(window[1]))
 }
 
+/// Output of trading `amount_in` at the spot price of a constant product
+/// pool, ie. the ratio of its reserves, before any slippage.  Only valid
+/// for constant product curves, and offset curves once the offset is added
+/// to the token B reserve.
+pub fn constant_product_spot_amount_out(
+    amount_in: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+) -> Option<u128> {
+    amount_in
+        .checked_mul(swap_destination_amount)?
+        .checked_div(swap_source_amount)
+}
+
+/// Price impact in basis points of receiving `amount_out` instead of
+/// `spot_amount_out`, the output at the spot price of the pool.  For a split
+/// trade, pass the sums over all hops to get the aggregate impact.
+pub fn price_impact_bps(amount_out: u128, spot_amount_out: u128) -> Option<u128> {
+    if spot_amount_out == 0 {
+        return None;
+    }
+    if amount_out >= spot_amount_out {
+        return Some(0);
+    }
+    spot_amount_out
+        .checked_sub(amount_out)?
+        .checked_mul(BASIS_POINTS)?
+        .checked_div(spot_amount_out)
+}
+
+/// Convert a u64 into a u128
 pub fn to_u128(val: u64) -> Result<u128, SwapError> {
     val.try_into().map_err(|_| SwapError::ConversionFailure)
 }
@@ -118,3 +349,448 @@ fn test() {
     let res = find_distribution(partition, amounts.as_slice());
     println!("{:?}", res.as_slice());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        curve::{base::CurveType, constant_product::ConstantProductCurve, stable::StableCurve},
+        state::SwapV1,
+    };
+
+    fn constant_product_swap(fees: Fees) -> SwapV1 {
+        SwapV1 {
+            is_initialized: true,
+            fees,
+            swap_curve: SwapCurve {
+                curve_type: CurveType::ConstantProduct,
+                calculator: Box::new(ConstantProductCurve),
+            },
+            ..SwapV1::default()
+        }
+    }
+
+    fn check_against_curve(token_swap: &SwapV1, source_amount: u64, dest_amount: u64) {
+        let in_amounts = interpolation(1_000_000, 10);
+        let results = calculate_swap_return(
+            token_swap,
+            &in_amounts,
+            source_amount,
+            dest_amount,
+            TradeDirection::AtoB,
+        )
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .unwrap();
+        assert_eq!(results.len(), in_amounts.len());
+        for (result, &amount_in) in results.iter().zip(in_amounts.iter()) {
+            let expected = token_swap
+                .swap_curve
+                .swap(
+                    amount_in as u128,
+                    source_amount as u128,
+                    dest_amount as u128,
+                    TradeDirection::AtoB,
+                    &token_swap.fees,
+                )
+                .unwrap();
+            assert_eq!(*result, expected);
+        }
+        // deeper levels must never give less
+        for pair in results.windows(2) {
+            assert!(pair[1].destination_amount_swapped >= pair[0].destination_amount_swapped);
+        }
+    }
+
+    #[test]
+    fn interpolation_of_large_amounts() {
+        assert_eq!(interpolation(10, 4), vec![2, 5, 7, 10]);
+        let in_amount = 200_000_000_000_000_000;
+        let in_amounts = interpolation(in_amount, 100);
+        assert_eq!(in_amounts[0], in_amount / 100);
+        assert_eq!(in_amounts[99], in_amount);
+        assert_eq!(interpolation(u64::MAX, 3)[2], u64::MAX);
+    }
+
+    #[test]
+    fn swap_return_matches_curve_without_fees() {
+        let token_swap = constant_product_swap(Fees::default());
+        check_against_curve(&token_swap, 50_000_000, 20_000_000);
+    }
+
+    #[test]
+    fn swap_return_matches_curve_with_fees() {
+        let token_swap = constant_product_swap(Fees {
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 5,
+            owner_trade_fee_denominator: 10_000,
+            ..Fees::default()
+        });
+        check_against_curve(&token_swap, 7_000_000, 300_000_000);
+    }
+
+    fn swap_return_row(results: &[Option<SwapResult>]) -> Vec<i128> {
+        distribution_row(results.iter().map(|result| {
+            result
+                .as_ref()
+                .map(|result| result.destination_amount_swapped)
+        }))
+    }
+
+    #[test]
+    fn swap_return_with_fewer_tokens_than_parts() {
+        let token_swap = constant_product_swap(Fees::default());
+        // the first depths are 0 and 1 token, which give nothing
+        let in_amounts = interpolation(5, 10);
+        let results = calculate_swap_return(
+            &token_swap,
+            &in_amounts,
+            1_000_000,
+            1_000_000,
+            TradeDirection::AtoB,
+        );
+        assert_eq!(results[0], None);
+        assert_eq!(results[1], None);
+        let expected = token_swap
+            .swap_curve
+            .swap(
+                5,
+                1_000_000,
+                1_000_000,
+                TradeDirection::AtoB,
+                &token_swap.fees,
+            )
+            .unwrap();
+        assert_eq!(results[9].as_ref(), Some(&expected));
+
+        // the pool still takes the whole amount
+        let row = swap_return_row(&results);
+        assert_eq!(row[1], MIN_VALUE);
+        let distribution = find_distribution(10, &[row.as_slice()]);
+        assert_eq!(distribution, vec![10]);
+        assert_eq!(
+            get_real_out_amount(&distribution, &[row.as_slice()]),
+            expected.destination_amount_swapped as i128
+        );
+    }
+
+    #[test]
+    fn swap_return_with_empty_output() {
+        let token_swap = constant_product_swap(Fees::default());
+        let in_amounts = interpolation(10, 10);
+        let results = calculate_swap_return(
+            &token_swap,
+            &in_amounts,
+            1_000_000,
+            1_000,
+            TradeDirection::AtoB,
+        );
+        assert!(results.iter().all(Option::is_none));
+        let row = swap_return_row(&results);
+        let distribution = find_distribution(10, &[row.as_slice()]);
+        assert!(get_real_out_amount(&distribution, &[row.as_slice()]) < 0);
+    }
+
+    fn check_amount_in(token_swap: &SwapV1, source_amount: u64, dest_amount: u64) {
+        for &trade_direction in &[TradeDirection::AtoB, TradeDirection::BtoA] {
+            for &amount_out in &[1, 7, 1_000, 123_456, dest_amount / 2] {
+                let amount_in = calculate_swap_amount_in(
+                    token_swap,
+                    amount_out,
+                    source_amount,
+                    dest_amount,
+                    trade_direction,
+                )
+                .unwrap();
+                let quote = |amount_in: u64| {
+                    calculate_swap_return(
+                        token_swap,
+                        &[amount_in],
+                        source_amount,
+                        dest_amount,
+                        trade_direction,
+                    )[0]
+                    .as_ref()
+                    .map_or(0, |result| result.destination_amount_swapped)
+                };
+                assert!(quote(amount_in) >= amount_out as u128);
+                assert!(quote(amount_in - 1) < amount_out as u128);
+            }
+        }
+    }
+
+    #[test]
+    fn swap_amount_in_matches_forward_quote() {
+        check_amount_in(
+            &constant_product_swap(Fees::default()),
+            5_000_000,
+            2_000_000,
+        );
+        let token_swap = constant_product_swap(Fees {
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 5,
+            owner_trade_fee_denominator: 10_000,
+            ..Fees::default()
+        });
+        check_amount_in(&token_swap, 7_000_000, 300_000_000);
+        let token_swap = SwapV1 {
+            swap_curve: SwapCurve {
+                curve_type: CurveType::Stable,
+                calculator: Box::new(StableCurve { amp: 100 }),
+            },
+            ..token_swap
+        };
+        check_amount_in(&token_swap, 300_000_000, 300_000_000);
+    }
+
+    #[test]
+    fn swap_amount_in_beyond_reserves() {
+        let token_swap = constant_product_swap(Fees::default());
+        assert_eq!(
+            calculate_swap_amount_in(&token_swap, 0, 1_000_000, 1_000_000, TradeDirection::AtoB),
+            Some(0)
+        );
+        assert_eq!(
+            calculate_swap_amount_in(
+                &token_swap,
+                1_000_000,
+                1_000_000,
+                1_000_000,
+                TradeDirection::AtoB
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn price_impact() {
+        let spot = constant_product_spot_amount_out(1_000, 1_000_000, 2_000_000).unwrap();
+        assert_eq!(spot, 2_000);
+        assert_eq!(price_impact_bps(2_000, spot), Some(0));
+        assert_eq!(price_impact_bps(1_990, spot), Some(50));
+        assert_eq!(price_impact_bps(1_000, spot), Some(5_000));
+        assert_eq!(price_impact_bps(1_000, 0), None);
+        assert_eq!(constant_product_spot_amount_out(1_000, 0, 2_000_000), None);
+    }
+
+    #[test]
+    fn price_impact_of_constant_product_swap() {
+        let token_swap = constant_product_swap(Fees::default());
+        let results = calculate_swap_return(
+            &token_swap,
+            &[10_000, 100_000],
+            1_000_000,
+            1_000_000,
+            TradeDirection::AtoB,
+        )
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .unwrap();
+        let small = price_impact_bps(
+            results[0].destination_amount_swapped,
+            constant_product_spot_amount_out(10_000, 1_000_000, 1_000_000).unwrap(),
+        )
+        .unwrap();
+        let large = price_impact_bps(
+            results[1].destination_amount_swapped,
+            constant_product_spot_amount_out(100_000, 1_000_000, 1_000_000).unwrap(),
+        )
+        .unwrap();
+        // x * y = k gives an impact of roughly amount_in / reserve
+        assert!((99..=100).contains(&small));
+        assert!((909..=910).contains(&large));
+    }
+
+    #[test]
+    fn distribution_without_cost_splits() {
+        // both dexes have the same concave output
+        let amounts: &[i128] = &[0, 100, 190, 270, 340];
+        let distribution = find_distribution(4, &[amounts, amounts]);
+        assert_eq!(distribution, vec![2, 2]);
+        assert_eq!(get_real_out_amount(&distribution, &[amounts, amounts]), 380);
+    }
+
+    #[test]
+    fn distribution_with_cost_collapses_small_gains() {
+        let amounts: &[i128] = &[0, 100, 190, 270, 340];
+        // splitting gains 40 over a single dex, a cost of 50 outweighs it
+        let distribution = find_distribution_with_cost(4, &[amounts, amounts], &[50, 50]);
+        assert_eq!(distribution.iter().filter(|&&parts| parts > 0).count(), 1);
+        assert_eq!(distribution.iter().sum::<u64>(), 4);
+
+        // a cost of 30 still leaves the split worthwhile
+        let distribution = find_distribution_with_cost(4, &[amounts, amounts], &[30, 30]);
+        assert_eq!(distribution, vec![2, 2]);
+    }
+
+    /// Straightforward version of the search, checking every split
+    fn reference_distribution(partition: u64, amounts: &[&[i128]], costs: &[i128]) -> i128 {
+        let width = partition as usize + 1;
+        let mut answer: Vec<i128> = (0..width)
+            .map(|j| {
+                if j == 0 {
+                    amounts[0][0]
+                } else {
+                    amounts[0][j] - costs[0]
+                }
+            })
+            .collect();
+        for i in 1..amounts.len() {
+            answer = (0..width)
+                .map(|j| {
+                    (1..=j)
+                        .map(|k| answer[j - k] + amounts[i][k] - costs[i])
+                        .fold(answer[j], i128::max)
+                })
+                .collect();
+        }
+        answer[partition as usize]
+    }
+
+    #[test]
+    fn distribution_matches_reference() {
+        let mut seed = 42u64;
+        let mut next = || {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+            (seed >> 33) as i128 % 1_000
+        };
+        for &(dex_count, partition) in &[(1, 5), (2, 10), (3, 20), (5, 16), (8, 30)] {
+            let rows: Vec<Vec<i128>> = (0..dex_count)
+                .map(|_| {
+                    let mut total = 0;
+                    (0..=partition)
+                        .map(|k| {
+                            if k > 0 {
+                                total += next();
+                            }
+                            total
+                        })
+                        .collect()
+                })
+                .collect();
+            let amounts: Vec<&[i128]> = rows.iter().map(|row| row.as_slice()).collect();
+            let costs: Vec<i128> = (0..dex_count).map(|_| next() % 100).collect();
+            let distribution = find_distribution_with_cost(partition, &amounts, &costs);
+            assert_eq!(distribution.iter().sum::<u64>(), partition);
+            let total: i128 = distribution
+                .iter()
+                .zip(amounts.iter().zip(costs.iter()))
+                .map(|(&parts, (row, &cost))| {
+                    if parts > 0 {
+                        row[parts as usize] - cost
+                    } else {
+                        0
+                    }
+                })
+                .sum();
+            assert_eq!(total, reference_distribution(partition, &amounts, &costs));
+        }
+    }
+
+    #[test]
+    fn distribution_of_concave_rows_matches_reference() {
+        // same shape as the quotes of constant product pools of different depths
+        let quotes = |partition: u64, depth: i128| -> Vec<i128> {
+            (0..=partition as i128)
+                .map(|k| depth * k * 1_000_000 / (depth + k * 1_000))
+                .collect()
+        };
+        for &(dex_count, partition) in &[(2, 10), (3, 7), (4, 50), (8, 100)] {
+            let rows: Vec<Vec<i128>> = (0..dex_count)
+                .map(|i| quotes(partition, (i as i128 % 3 + 1) * 10_000))
+                .collect();
+            let amounts: Vec<&[i128]> = rows.iter().map(|row| row.as_slice()).collect();
+            for &cost in &[0, 5_000] {
+                let costs = vec![cost; dex_count];
+                let distribution = find_distribution_with_cost(partition, &amounts, &costs);
+                assert_eq!(distribution.iter().sum::<u64>(), partition);
+                let total: i128 = distribution
+                    .iter()
+                    .zip(amounts.iter())
+                    .filter(|(&parts, _)| parts > 0)
+                    .map(|(&parts, row)| row[parts as usize] - cost)
+                    .sum();
+                assert_eq!(total, reference_distribution(partition, &amounts, &costs));
+            }
+        }
+
+        // equal pools tie at every split and share the parts evenly
+        let row = quotes(9, 10_000);
+        let distribution = find_distribution(9, &[&row, &row, &row]);
+        assert_eq!(distribution, vec![3, 3, 3]);
+    }
+
+    #[test]
+    fn concave_rows() {
+        assert!(is_concave(&[0, 100, 190, 270, 340]));
+        assert!(is_concave(&[0, 12, 15, MIN_VALUE]));
+        assert!(is_concave(&[5, 5]));
+        assert!(!is_concave(&[0, 10, 30]));
+        assert!(!is_concave(&[0, MIN_VALUE, MIN_VALUE, 30, 40]));
+    }
+
+    #[test]
+    fn distribution_into_checks_buffers() {
+        let amounts: &[i128] = &[0, 100, 190, 270, 340];
+        let (answer_len, parent_len) = distribution_buffer_lens(4, 2);
+        let mut answer = vec![0i128; answer_len];
+        let mut parent = vec![0u64; parent_len];
+        let mut distribution = vec![0u64; 2];
+        assert_eq!(
+            find_distribution_into(
+                4,
+                &[amounts, amounts],
+                &[0, 0],
+                &mut answer,
+                &mut parent,
+                &mut distribution,
+            ),
+            Ok(())
+        );
+        assert_eq!(distribution, vec![2, 2]);
+        assert_eq!(
+            find_distribution_into(
+                4,
+                &[amounts, amounts],
+                &[0, 0],
+                &mut answer[..answer_len - 1],
+                &mut parent,
+                &mut distribution,
+            ),
+            Err(SwapError::InvalidInput)
+        );
+        assert_eq!(
+            find_distribution_into(
+                5,
+                &[amounts, amounts],
+                &[0, 0],
+                &mut answer,
+                &mut parent,
+                &mut distribution,
+            ),
+            Err(SwapError::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn distribution_skips_unquotable_depths() {
+        let first = distribution_row(vec![None, None, Some(30), Some(40)]);
+        let second = distribution_row(vec![Some(12), Some(15), None, None]);
+        assert_eq!(first, vec![0, MIN_VALUE, MIN_VALUE, 30, 40]);
+        let distribution = find_distribution(4, &[first.as_slice(), second.as_slice()]);
+        assert_eq!(distribution, vec![3, 1]);
+        assert_eq!(
+            get_real_out_amount(&distribution, &[first.as_slice(), second.as_slice()]),
+            42
+        );
+    }
+
+    #[test]
+    fn distribution_prefers_cheaper_dex() {
+        let amounts: &[i128] = &[0, 100, 190, 270, 340];
+        let distribution = find_distribution_with_cost(4, &[amounts, amounts], &[100, 0]);
+        assert_eq!(distribution, vec![0, 4]);
+    }
+}